@@ -0,0 +1,84 @@
+//! Single-character bookmarks for table positions, modelled on the `m`/`'`
+//! marks of terminal file managers. Marks are keyed by the canonicalized
+//! input path so they survive across sessions on the same file.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+/// A set of row positions keyed by a single character, backed by a shared
+/// dotfile in the user's home directory.
+pub struct Bookmarks {
+    marks: HashMap<char, i64>,
+    store_path: PathBuf,
+    key: String,
+}
+
+impl Bookmarks {
+    /// Load the marks recorded for `input` from `~/.csv_editor_bookmarks`.
+    pub fn load<P>(input: P) -> Bookmarks
+    where P: AsRef<Path>
+    {
+        let key = input.as_ref().canonicalize()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| input.as_ref().to_string_lossy().into_owned());
+        let store_path = store_path();
+        let mut marks: HashMap<char, i64> = HashMap::new();
+        if let Ok(file) = File::open(&store_path) {
+            for line in BufReader::new(file).lines().flatten() {
+                if let Some((k, ch, rowid)) = parse_line(&line) {
+                    if k == key {
+                        marks.insert(ch, rowid);
+                    }
+                }
+            }
+        }
+        Bookmarks{marks: marks, store_path: store_path, key: key}
+    }
+    /// Record a mark and persist the whole set back to the dotfile.
+    pub fn set(&mut self, ch: char, rowid: i64) {
+        self.marks.insert(ch, rowid);
+        self.persist();
+    }
+    /// Look up the row recorded under `ch`, if any.
+    pub fn get(&self, ch: char) -> Option<i64> {
+        self.marks.get(&ch).copied()
+    }
+    fn persist(&self) {
+        // Keep marks belonging to other files, replace ours.
+        let mut kept: Vec<String> = Vec::new();
+        if let Ok(file) = File::open(&self.store_path) {
+            for line in BufReader::new(file).lines().flatten() {
+                match parse_line(&line) {
+                    Some((k, _, _)) if k == self.key => {},
+                    _ if line.trim().is_empty() => {},
+                    _ => kept.push(line),
+                }
+            }
+        }
+        let mut file = match File::create(&self.store_path) {
+            Ok(file) => file,
+            Err(_) => return
+        };
+        for line in &kept {
+            let _ = writeln!(file, "{}", line);
+        }
+        for (ch, &rowid) in &self.marks {
+            let _ = writeln!(file, "{}\t{}\t{}", self.key, ch, rowid);
+        }
+    }
+}
+
+fn store_path() -> PathBuf {
+    let home = ::std::env::var("HOME").unwrap_or_else(|_| String::from("."));
+    Path::new(&home).join(".csv_editor_bookmarks")
+}
+
+fn parse_line(line: &str) -> Option<(String, char, i64)> {
+    let mut fields = line.splitn(3, '\t');
+    let key = fields.next()?.to_string();
+    let ch = fields.next()?.chars().next()?;
+    let rowid = fields.next()?.parse::<i64>().ok()?;
+    Some((key, ch, rowid))
+}
@@ -6,6 +6,10 @@ extern crate env_logger;
 extern crate csv;
 extern crate cursive_table_view;
 
+mod bookmarks;
+
+use bookmarks::Bookmarks;
+
 use argparse::{ArgumentParser, Store, Print};
 
 use std::cmp::Ordering;
@@ -13,12 +17,14 @@ use std::io::prelude::*;
 use std::io::BufReader;
 use std::fs::File;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 
 use cursive::Cursive;
 use cursive::traits::*;
 use cursive::align::HAlign;
 use cursive::direction::Orientation;
-use cursive::views::{Dialog, LinearLayout};
+use cursive::event::{Event, Key};
+use cursive::views::{Dialog, EditView, LinearLayout, OnEventView, TextView};
 use cursive_table_view::{TableView, TableViewItem};
 
 #[derive(Clone, Debug)]
@@ -26,17 +32,36 @@ struct Cell {
     value: String,
 }
 
+/// Inferred type of a column, used to sort numerically instead of
+/// lexicographically.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ColumnKind {
+    Integer,
+    Float,
+    Text,
+}
+
 #[derive(Clone, Debug)]
 struct Row {
     cells: Vec<Cell>,
-    rowid: i64
+    rowid: i64,
+    kinds: Rc<Vec<ColumnKind>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Dialect {
+    delimiter: u8,
+    quote: u8,
+    trim: csv::Trim,
 }
 
 #[derive(Debug)]
 struct Table {
     header: Row,
+    raw_header: Row,
     rows: Vec<Row>,
     num_cols: usize,
+    kinds: Rc<Vec<ColumnKind>>,
 }
 
 enum Error {
@@ -116,10 +141,10 @@ impl Row {
             None => missing
         }
     }
-    fn from_line(s: String) -> Row {
-        debug!("Row::from_line: {}", s.trim());
-        let mut newself: Row = Row{cells: Vec::new(), rowid: -1};
-        for term in s.split(',') {
+    fn from_record(record: &csv::StringRecord) -> Row {
+        debug!("Row::from_record: {:?}", record);
+        let mut newself: Row = Row{cells: Vec::new(), rowid: -1, kinds: Rc::new(Vec::new())};
+        for term in record.iter() {
             newself.add_cell(Cell::from_string(term));
         }
         return newself;
@@ -170,28 +195,123 @@ impl Table {
         let w2 = self.header.cells[c].len() + header_padding;
         w.max(w2)
     }
-    fn from_filepath<P>(filepath: P) -> Result<Table, Error>
-    where P: AsRef<Path>
+    fn from_reader<R>(reader: R, dialect: &Dialect) -> Table
+    where R: BufRead
     {
-        let file = match File::open(&filepath) {
-            Err(why) => return Err(Error::from_bad_file(filepath, why)),
-            Ok(file) => file
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .delimiter(dialect.delimiter)
+            .quote(dialect.quote)
+            .trim(dialect.trim)
+            .from_reader(reader);
+        let mut records = reader.records();
+        let header = match records.next() {
+            Some(record) => Row::from_record(&record.expect("failed to read input")),
+            None => Row{cells: Vec::new(), rowid: -1, kinds: Rc::new(Vec::new())}
         };
-        let mut buf = BufReader::new(file);
-        let mut header: String = String::new();
-        buf.read_line(&mut header).expect("failed to read from file");
         let mut newself: Table = Table {
-            header: Row::from_line(header),
+            raw_header: header.clone(),
+            header: header,
             rows: Vec::new(),
-            num_cols: 0
+            num_cols: 0,
+            kinds: Rc::new(Vec::new())
         };
-        for ln in buf.lines().map(|ln| ln.unwrap()) {
-            if ln.len() > 0 {
-                newself.add_line(Row::from_line(ln))
+        for record in records.map(|record| record.unwrap()) {
+            if record.len() > 0 {
+                newself.add_line(Row::from_record(&record))
             }
         }
         newself.fix_header_names();
-        Ok(newself)
+        newself.classify_columns();
+        newself
+    }
+    fn from_filepath<P>(filepath: P, dialect: &Dialect) -> Result<Table, Error>
+    where P: AsRef<Path>
+    {
+        if filepath.as_ref() == Path::new("-") {
+            let stdin = ::std::io::stdin();
+            return Ok(Table::from_reader(stdin.lock(), dialect));
+        }
+        let file = match File::open(&filepath) {
+            Err(why) => return Err(Error::from_bad_file(filepath, why)),
+            Ok(file) => file
+        };
+        Ok(Table::from_reader(BufReader::new(file), dialect))
+    }
+    fn matching_rows(&self, needle: &str, col: Option<usize>) -> Vec<Row> {
+        let needle = needle.to_lowercase();
+        self.rows.iter().filter(|row| {
+            let strings = row.to_strings();
+            match col {
+                Some(c) => strings.get(c)
+                    .map_or(false, |s| s.to_lowercase().contains(&needle)),
+                None => strings.iter().any(|s| s.to_lowercase().contains(&needle))
+            }
+        }).cloned().collect()
+    }
+    fn classify_columns(&mut self) {
+        let mut kinds: Vec<ColumnKind> = Vec::with_capacity(self.num_cols);
+        for c in 0..self.num_cols {
+            let mut kind = ColumnKind::Integer;
+            let mut any = false;
+            for row in &self.rows {
+                let v = row.try_get(c, "");
+                if v.is_empty() {
+                    continue;
+                }
+                any = true;
+                if kind == ColumnKind::Integer && v.parse::<i64>().is_err() {
+                    kind = ColumnKind::Float;
+                }
+                if kind == ColumnKind::Float && v.parse::<f64>().is_err() {
+                    kind = ColumnKind::Text;
+                    break;
+                }
+            }
+            kinds.push(if any { kind } else { ColumnKind::Text });
+        }
+        let kinds = Rc::new(kinds);
+        self.kinds = kinds.clone();
+        for row in &mut self.rows {
+            row.kinds = kinds.clone();
+        }
+    }
+    fn set_cell(&mut self, rowid: i64, col: usize, value: &str) {
+        if let Some(row) = self.rows.iter_mut().find(|r| r.rowid() == rowid) {
+            while row.num_cols() <= col {
+                row.add_cell(Cell::from_string(""));
+            }
+            row.cells[col].set_value(value);
+        }
+    }
+    fn write_to_path<P>(&self, filepath: P, dialect: &Dialect) -> Result<(), Error>
+    where P: AsRef<Path>
+    {
+        let mut writer = csv::WriterBuilder::new()
+            .flexible(true)
+            .delimiter(dialect.delimiter)
+            .quote(dialect.quote)
+            .from_writer(Vec::new());
+        let write = |writer: &mut csv::Writer<Vec<u8>>| -> ::std::io::Result<Vec<u8>> {
+            // Write back the header as originally read, not the `col:N`-padded
+            // display header, so blank header cells stay blank. Cells are
+            // written as loaded, that is with surrounding whitespace trimmed.
+            writer.write_record(self.raw_header.to_strings())?;
+            for row in &self.rows {
+                writer.write_record(row.to_strings())?;
+            }
+            writer.flush()?;
+            Ok(writer.get_ref().clone())
+        };
+        match write(&mut writer) {
+            Ok(bytes) => match File::create(&filepath) {
+                Ok(mut file) => file.write_all(&bytes)
+                    .map_err(|why| Error::from_bad_file(filepath, why)),
+                Err(why) => Err(Error::from_bad_file(filepath, why))
+            },
+            Err(why) => Err(Error::from_bad_file(filepath, why))
+        }
     }
     fn create_table_view(&self) -> TableView<Row, BasicColumn> {
         let mut tv = TableView::<Row, BasicColumn>::new();
@@ -235,23 +355,188 @@ impl TableViewItem<BasicColumn> for Row {
                 self.rowid.cmp(&other.rowid)
             },
             BasicColumn::ColumnPos{c} => {
-                let lhs = &self.cells[c].value;
-                let rhs = &other.cells[c].value;
-                lhs.cmp(rhs)
+                let lhs = self.try_get(c, "");
+                let rhs = other.try_get(c, "");
+                // empty / missing cells always order last
+                match (lhs.is_empty(), rhs.is_empty()) {
+                    (true, true) => return Ordering::Equal,
+                    (true, false) => return Ordering::Greater,
+                    (false, true) => return Ordering::Less,
+                    (false, false) => {}
+                }
+                match self.kinds.get(c).copied().unwrap_or(ColumnKind::Text) {
+                    ColumnKind::Integer => match (lhs.parse::<i64>(), rhs.parse::<i64>()) {
+                        (Ok(l), Ok(r)) => l.cmp(&r),
+                        _ => lhs.cmp(rhs)
+                    },
+                    ColumnKind::Float => match (lhs.parse::<f64>(), rhs.parse::<f64>()) {
+                        (Ok(l), Ok(r)) => l.partial_cmp(&r).unwrap_or(Ordering::Equal),
+                        _ => lhs.cmp(rhs)
+                    },
+                    ColumnKind::Text => lhs.cmp(rhs)
+                }
             }
         }
     }
 }
 
+/// The authoritative, mutable state kept in `siv.set_user_data`; the live
+/// `TableView` only ever holds clones, so edits are applied here and the view
+/// is rebuilt from `table.rows`.
+struct AppState {
+    table: Table,
+    dialect: Dialect,
+    filepath: String,
+    bookmarks: Bookmarks,
+}
+
+fn set_table_items(siv: &mut Cursive) {
+    let rows = siv.with_user_data(|s: &mut AppState| s.table.rows.clone()).unwrap();
+    siv.call_on_name("table", |tv: &mut TableView<Row, BasicColumn>| {
+        tv.set_items(rows);
+    });
+}
+
+fn open_edit_dialog(siv: &mut Cursive, row: Row) {
+    let rowid = row.rowid();
+    let headers = siv.with_user_data(|s: &mut AppState| s.table.header_names()
+        .iter().map(|h| h.to_string()).collect::<Vec<String>>()).unwrap();
+    let ncols = headers.len();
+    let mut layout = LinearLayout::new(Orientation::Vertical);
+    for (c, name) in headers.iter().enumerate() {
+        layout.add_child(TextView::new(name.as_str()));
+        layout.add_child(EditView::new()
+            .content(row.try_get(c, ""))
+            .with_name(format!("edit_{}", c)));
+    }
+    let dialog = Dialog::around(layout)
+        .title(format!("edit row {}", rowid))
+        .button("save", move |siv| {
+            for c in 0..ncols {
+                let value = siv.call_on_name(&format!("edit_{}", c),
+                    |v: &mut EditView| v.get_content()).unwrap();
+                siv.with_user_data(|s: &mut AppState| s.table.set_cell(rowid, c, &value));
+            }
+            set_table_items(siv);
+            siv.pop_layer();
+        })
+        .button("cancel", |siv| { siv.pop_layer(); });
+    siv.add_layer(dialog);
+}
+
+/// Prompt for a single character, then record the current selection under it.
+fn open_mark_prompt(siv: &mut Cursive) {
+    let editor = EditView::new().on_edit(|siv, text, _| {
+        if let Some(ch) = text.chars().next() {
+            siv.pop_layer();
+            record_bookmark(siv, ch);
+        }
+    });
+    siv.add_layer(Dialog::around(editor).title("mark"));
+}
+
+/// Prompt for a single character, then jump to the position stored under it.
+fn open_goto_prompt(siv: &mut Cursive) {
+    let editor = EditView::new().on_edit(|siv, text, _| {
+        if let Some(ch) = text.chars().next() {
+            siv.pop_layer();
+            jump_bookmark(siv, ch);
+        }
+    });
+    siv.add_layer(Dialog::around(editor).title("goto mark"));
+}
+
+fn record_bookmark(siv: &mut Cursive, ch: char) {
+    // The `TableView` only tracks a selected row, so a bookmark is just a rowid.
+    let selection = siv.call_on_name("table", |tv: &mut TableView<Row, BasicColumn>| {
+        tv.item().and_then(|i| tv.borrow_item(i).map(|r| r.rowid()))
+    }).flatten();
+    if let Some(rowid) = selection {
+        siv.with_user_data(|s: &mut AppState| s.bookmarks.set(ch, rowid));
+    }
+}
+
+fn jump_bookmark(siv: &mut Cursive, ch: char) {
+    let target = siv.with_user_data(|s: &mut AppState| s.bookmarks.get(ch)).flatten();
+    if let Some(rowid) = target {
+        siv.call_on_name("table", |tv: &mut TableView<Row, BasicColumn>| {
+            if let Some(i) = tv.borrow_items().iter().position(|r| r.rowid() == rowid) {
+                tv.set_selected_item(i);
+            }
+        });
+    }
+}
+
+fn open_search_dialog(siv: &mut Cursive) {
+    let search = EditView::new()
+        .on_edit(|siv, text, _| {
+            let rows = siv.with_user_data(|s: &mut AppState| {
+                s.table.matching_rows(text, None)
+            }).unwrap();
+            siv.call_on_name("table", |tv: &mut TableView<Row, BasicColumn>| {
+                tv.set_items(rows);
+            });
+        })
+        .on_submit(|siv, _| { siv.pop_layer(); })
+        .min_width(20);
+    // Esc abandons the search and restores the full set of rows.
+    let search = OnEventView::new(search)
+        .on_event(Key::Esc, |siv| {
+            set_table_items(siv);
+            siv.pop_layer();
+        });
+    siv.add_layer(Dialog::around(search).title("search"));
+}
+
+fn save_table(siv: &mut Cursive) {
+    let result = siv.with_user_data(|s: &mut AppState| {
+        s.table.write_to_path(&s.filepath, &s.dialect)
+            .map(|_| s.filepath.clone())
+    }).unwrap();
+    let message = match result {
+        Ok(path) => format!("wrote '{}'", path),
+        Err(e) => format!("save failed: {}", e.get_message().unwrap_or_default())
+    };
+    siv.add_layer(Dialog::info(message));
+}
+
+fn parse_byte(s: &str) -> u8 {
+    match s {
+        "\\t" => b'\t',
+        _ => s.bytes().next().unwrap_or(b',')
+    }
+}
+
+fn parse_trim(s: &str) -> csv::Trim {
+    match s.to_lowercase().as_str() {
+        "headers" => csv::Trim::Headers,
+        "fields" => csv::Trim::Fields,
+        "all" => csv::Trim::All,
+        _ => csv::Trim::None
+    }
+}
+
 fn main() {
     fn body() -> Result<i32, Error> {
         let _ = env_logger::init();
         let mut filepath: String = String::new();
+        let mut delimiter: String = String::from(",");
+        let mut quote: String = String::from("\"");
+        let mut trim: String = String::from("none");
         {
             let mut ap = ArgumentParser::new();
             ap.set_description("view a csv file in a table (ncurses)");
             ap.add_option(&["-V", "--version"],
                 Print(env!("CARGO_PKG_VERSION").to_string()), "Show Version");
+            ap.refer(&mut delimiter)
+                .add_option(&["-d", "--delimiter"], Store,
+                    "field delimiter, e.g. ',' ';' '|' or '\\t' (default ',')");
+            ap.refer(&mut quote)
+                .add_option(&["-q", "--quote"], Store,
+                    "quote character (default '\"')");
+            ap.refer(&mut trim)
+                .add_option(&["-t", "--trim"], Store,
+                    "whitespace trimming: none, headers, fields, all (default none)");
             ap.refer(&mut filepath).required()
                 .add_argument("file", Store, "filepath to .csv, use '-' to read from STDIN");
             match ap.parse_args() {
@@ -259,15 +544,37 @@ fn main() {
                 Err(x) => return Err(Error::ExitCode(x))
             }
         }
-        let table = Table::from_filepath(filepath)?;
+        let dialect = Dialect {
+            delimiter: parse_byte(&delimiter),
+            quote: parse_byte(&quote),
+            trim: parse_trim(&trim),
+        };
+        let table = Table::from_filepath(filepath.as_str(), &dialect)?;
         info!("num_rows={}", table.rows.len());
 
         let mut siv = Cursive::new();
         let mut layout = LinearLayout::new(Orientation::Horizontal);
         let sum_colwidth: usize = table.sum_colwidth2("<NULL>".len(), 2, 2 + 4);
         let num_rows = table.num_rows() + 4;
-        layout.add_child(table.create_table_view().min_size((sum_colwidth, num_rows)));
+        let mut tv = table.create_table_view();
+        tv.set_on_submit(|siv, _row, index| {
+            let item = siv.call_on_name("table", |tv: &mut TableView<Row, BasicColumn>| {
+                tv.borrow_item(index).cloned()
+            }).unwrap();
+            if let Some(row) = item {
+                open_edit_dialog(siv, row);
+            }
+        });
+        layout.add_child(tv.with_name("table").min_size((sum_colwidth, num_rows)));
         siv.add_layer(Dialog::around(layout).title("csvView"));
+        let bookmarks = Bookmarks::load(&filepath);
+        siv.set_user_data(AppState{
+            table: table, dialect: dialect, filepath: filepath, bookmarks: bookmarks
+        });
+        siv.add_global_callback(Event::CtrlChar('s'), save_table);
+        siv.add_global_callback(Event::Char('/'), open_search_dialog);
+        siv.add_global_callback(Event::Char('m'), open_mark_prompt);
+        siv.add_global_callback(Event::Char('\''), open_goto_prompt);
         siv.run();
         Ok(0)
     }